@@ -5,3 +5,4 @@ from .external_usage import (
     expr_externally_used,
 )
 from .topo import topo_downstream_modules, get_upstreams
+from .typecheck import TypeViolation, check_expr, typecheck
@@ -0,0 +1,90 @@
+"""Operand width checks for the expressions of an Assassyn system."""
+
+from __future__ import annotations
+
+from dataclasses import dataclass
+from typing import Iterable, List
+
+from ..ir.array import Slice
+from ..ir.expr import BinaryOp, Cast, Expr
+
+# MUL widens to the sum of its operand widths, and a shift amount is independent
+# of the shifted width, so neither needs matching operands.
+_SAME_WIDTH_OPS = frozenset([
+    BinaryOp.ADD, BinaryOp.SUB, BinaryOp.DIV, BinaryOp.MOD,
+    BinaryOp.BITWISE_AND, BinaryOp.BITWISE_OR, BinaryOp.BITWISE_XOR,
+    BinaryOp.ILT, BinaryOp.IGT, BinaryOp.ILE, BinaryOp.IGE, BinaryOp.EQ, BinaryOp.NEQ,
+])
+
+
+@dataclass(frozen=True)
+class TypeViolation:
+    """One operand that breaks the width rules of its expression's opcode."""
+
+    expr: Expr
+    opcode: str
+    expected: str
+    actual: str
+
+    def __str__(self):
+        return (
+            f'{self.expr.as_operand()} ({self.opcode}) at {self.expr.loc}: '
+            f'expected {self.expected}, got {self.actual}'
+        )
+
+
+def _check_binary(expr: BinaryOp) -> Iterable[TypeViolation]:
+    if expr.opcode not in _SAME_WIDTH_OPS:
+        return
+    lhs_bits = expr.lhs.dtype.bits
+    rhs_bits = expr.rhs.dtype.bits
+    if lhs_bits != rhs_bits:
+        yield TypeViolation(
+            expr, BinaryOp.OPERATORS[expr.opcode],
+            f'operands of equal width ({lhs_bits} bits)', f'{lhs_bits} and {rhs_bits} bits',
+        )
+
+
+def _check_slice(expr: Slice) -> Iterable[TypeViolation]:
+    l = expr.l.value.value
+    r = expr.r.value.value
+    bits = expr.x.dtype.bits
+    if not l <= r < bits:
+        yield TypeViolation(
+            expr, 'slice', f'0 <= l <= r < {bits}', f'[{l}:{r}] of {bits} bits',
+        )
+
+
+def _check_cast(expr: Cast) -> Iterable[TypeViolation]:
+    src_bits = expr.x.dtype.bits
+    dst_bits = expr.dtype.bits
+    method = Cast.SUBCODES[expr.opcode]
+    if expr.opcode == Cast.BITCAST and src_bits != dst_bits:
+        yield TypeViolation(
+            expr, method, f'a {src_bits}-bit target', f'a {dst_bits}-bit target',
+        )
+    elif expr.opcode in (Cast.ZEXT, Cast.SEXT) and dst_bits < src_bits:
+        yield TypeViolation(
+            expr, method, f'a target of at least {src_bits} bits', f'{dst_bits} bits',
+        )
+
+
+def check_expr(expr: Expr) -> List[TypeViolation]:
+    """Return the width violations of a single expression."""
+    if isinstance(expr, BinaryOp):
+        return list(_check_binary(expr))
+    if isinstance(expr, Slice):
+        return list(_check_slice(expr))
+    if isinstance(expr, Cast):
+        return list(_check_cast(expr))
+    return []
+
+
+def typecheck(sys) -> List[TypeViolation]:
+    """Check every expression of *sys* and return all violations; empty means well-typed."""
+    violations: List[TypeViolation] = []
+    for module in list(sys.modules) + list(sys.downstreams):
+        for expr in getattr(module, 'body', None) or []:
+            if isinstance(expr, Expr):
+                violations.extend(check_expr(expr))
+    return violations
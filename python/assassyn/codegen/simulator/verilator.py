@@ -2,13 +2,12 @@
 
 from __future__ import annotations
 
+import hashlib
 import json
 import os
 import platform
-import shlex
 import shutil
 import subprocess
-import sys
 from dataclasses import dataclass, field
 from pathlib import Path
 from typing import Dict, Iterable, List, Optional
@@ -17,6 +16,34 @@ from ...ir.dtype import DType
 from ...ir.module.external import ExternalSV
 from ...utils import namify, repo_path
 from .utils import camelize
+from .verilator_build import (
+    aggregate_enabled,
+    compile_command,
+    dynamic_lib_suffix,
+    gather_source_files,
+    generate_sim_main_cpp,
+    keep_artifacts_root,
+    keep_build_artifacts,
+    library_path,
+    resolve_verilator_executable,
+    resolve_verilator_paths,
+    run_verilator_compile,
+    standalone_enabled,
+    trace_enabled,
+    verilator_cache_root,
+    verilator_command,
+    verilator_threads,
+    verilator_version,
+    write_aggregated_source,
+)
+from .verilator_trace import (
+    lib_rs_trace_fields,
+    lib_rs_trace_inits,
+    lib_rs_trace_loads,
+    lib_rs_trace_methods,
+    wrapper_trace_cpp,
+    wrapper_trace_init,
+)
 
 
 _C_INT_TYPES_UNSIGNED = {8: "uint8_t", 16: "uint16_t", 32: "uint32_t", 64: "uint64_t"}
@@ -53,6 +80,10 @@ class ExternalFFIModule:  # pylint: disable=too-many-instance-attributes
     outputs: List[FFIPort] = field(default_factory=list)
     has_clock: bool = False
     has_reset: bool = False
+    trace: bool = False
+    threads: int = 1
+    aggregate: bool = False
+    standalone: bool = False
     original_module_name: str = ""
     struct_name: str = ""
     definitions: Dict[str, str] = field(default_factory=dict)
@@ -111,51 +142,6 @@ def _write_file(path: Path, content: str) -> None:
     path.write_text(content, encoding="utf-8")
 
 
-def _dynamic_lib_suffix() -> str:
-    system = platform.system().lower()
-    if system == "windows":
-        return ".dll"
-    if system == "darwin":
-        return ".dylib"
-    return ".so"
-
-
-def _compiler_command() -> List[str]:
-    """Detect and return the appropriate C++ compiler command."""
-    # First, check if CXX environment variable is set
-    compiler_env = os.environ.get("CXX")
-    if compiler_env:
-        tokens = shlex.split(compiler_env)
-        if tokens:
-            return tokens
-    # Try to detect the system's default C++ compiler more intelligently
-    # Check for common compiler environment variables
-    for env_var in ["CXX", "CC"]:
-        if env_var in os.environ:
-            compiler_path = os.environ[env_var]
-            if compiler_path and shutil.which(compiler_path):
-                return [compiler_path]
-    # Fallback to common C++ compilers, but try to be more system-appropriate
-    candidates = []
-    # On macOS, prefer clang++ if available (it's the default)
-    if sys.platform == "darwin":
-        candidates = ["clang++", "g++", "c++"]
-    # On Linux, prefer c++ (generic) then g++, then clang++
-    elif sys.platform.startswith("linux"):
-        candidates = ["c++", "g++", "clang++"]
-    # On other systems, use a generic order
-    else:
-        candidates = ["c++", "g++", "clang++"]
-    for candidate in candidates:
-        path = shutil.which(candidate)
-        if path:
-            return [path]
-    raise RuntimeError(
-        "Unable to locate a C++ compiler. Please set the CXX environment variable "
-        "or install a C++ compiler (g++, clang++, or c++)."
-    )
-
-
 def _run_subprocess(cmd: List[str], cwd: Path | None = None) -> None:
     subprocess.run(cmd, check=True, cwd=cwd, env=os.environ.copy())
 
@@ -177,93 +163,6 @@ def _prepare_build_directory(crate: ExternalFFIModule) -> Path:
     return obj_dir
 
 
-def _run_verilator_compile(crate: ExternalFFIModule, sv_source: Path, obj_dir: Path) -> None:
-    """Invoke Verilator to generate the C++ model."""
-    verilator_exe = os.environ.get("ASSASSYN_VERILATOR", "verilator")
-    verilator_cmd = [
-        verilator_exe,
-        "--cc",
-        str(sv_source),
-        "--top-module",
-        crate.top_module,
-        "-O3",
-        "--Mdir",
-        str(obj_dir),
-    ]
-    _run_subprocess(verilator_cmd)
-
-
-def _resolve_verilator_paths() -> tuple[Path, Path]:
-    """Locate the Verilator include directories."""
-    verilator_root = os.environ.get("VERILATOR_ROOT")
-    if not verilator_root:
-        raise EnvironmentError(
-            "VERILATOR_ROOT is not set. Please run 'source setup.sh' before "
-            "generating external FFIs."
-        )
-    include_dir = Path(verilator_root) / "include"
-    if not include_dir.exists():
-        raise FileNotFoundError(f"Verilator include directory not found: {include_dir}")
-    return include_dir, include_dir / "vltstd"
-
-
-def _gather_source_files(
-    crate: ExternalFFIModule,
-    obj_dir: Path,
-    include_dir: Path,
-) -> List[Path]:
-    """Collect all C++ sources required to build the shared library."""
-    cpp_class = f"V{crate.top_module}"
-    aggregated = obj_dir / f"{cpp_class}__ALL.cpp"
-
-    source_files: List[Path] = []
-    if aggregated.exists():
-        source_files.append(aggregated)
-    else:
-        for path in sorted(obj_dir.glob("*.cpp")):
-            if path.name.endswith("__ALL.cpp"):
-                continue
-            source_files.append(path)
-
-    wrapper_src = crate.crate_path / "src" / "wrapper.cpp"
-    if not wrapper_src.exists():
-        raise FileNotFoundError(f"Wrapper source not found: {wrapper_src}")
-    source_files.append(wrapper_src)
-
-    runtime_sources = [include_dir / "verilated.cpp"]
-    for extra in ("verilated_threads.cpp", "verilated_dpi.cpp"):
-        extra_path = include_dir / extra
-        if extra_path.exists():
-            runtime_sources.append(extra_path)
-    source_files.extend(runtime_sources)
-    return source_files
-
-
-def _build_compile_command(
-    crate: ExternalFFIModule,
-    source_files: List[Path],
-    include_dir: Path,
-    vltstd_dir: Path,
-    obj_dir: Path,
-) -> tuple[List[str], str, Path]:
-    """Construct the compiler command for the shared library."""
-    compiler = _compiler_command()
-    compile_cmd = compiler + [
-        "-std=c++17",
-        "-shared",
-        "-fPIC",
-        "-O3",
-    ]
-    for include in (include_dir, vltstd_dir, obj_dir):
-        compile_cmd.extend(["-I", str(include)])
-    compile_cmd.extend(str(src) for src in source_files)
-
-    lib_filename = f"lib{crate.dynamic_lib_name}{_dynamic_lib_suffix()}"
-    lib_path = crate.crate_path / lib_filename
-    compile_cmd.extend(["-o", str(lib_path)])
-    return compile_cmd, lib_filename, lib_path
-
-
 def _unique_name(base: str, registry: Dict[str, int]) -> str:
     """Return a unique name derived from base and update the registry."""
     count = registry.get(base, 0)
@@ -350,6 +249,10 @@ def _create_external_spec(
         outputs=ports_out,
         has_clock=getattr(module, "has_clock", False),
         has_reset=getattr(module, "has_reset", False),
+        trace=trace_enabled(),
+        threads=verilator_threads(),
+        aggregate=aggregate_enabled(),
+        standalone=standalone_enabled(),
         original_module_name=module.name,
     )
 
@@ -411,6 +314,10 @@ def _create_external_spec_from_class(
         outputs=ports_out,
         has_clock=metadata.get("has_clock", False),
         has_reset=metadata.get("has_reset", False),
+        trace=trace_enabled(),
+        threads=verilator_threads(),
+        aggregate=aggregate_enabled(),
+        standalone=standalone_enabled(),
         original_module_name=external_class.__name__,
     )
 
@@ -426,6 +333,10 @@ def _spec_manifest_entry(spec: ExternalFFIModule, simulator_root: Path) -> Dict[
         "struct_name": spec.struct_name,
         "has_clock": spec.has_clock,
         "has_reset": spec.has_reset,
+        "trace": spec.trace,
+        "threads": spec.threads,
+        "aggregate": spec.aggregate,
+        "standalone": spec.standalone,
         "lib_filename": spec.lib_filename,
         "lib_path": str(spec.lib_path) if spec.lib_path else "",
         "inputs": [
@@ -475,6 +386,7 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
     lines = [
         "#![allow(dead_code)]",
         "use sim_runtime::libloading::Library;",
+        *(["use std::ffi::CString;"] if crate.trace else []),
         "use std::path::{Path, PathBuf};",
         "use std::ptr::NonNull;",
         "",
@@ -514,6 +426,9 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
         "    eval_fn: unsafe extern \"C\" fn(*mut ModuleHandle),",
     ]
 
+    if crate.trace:
+        lines.extend(lib_rs_trace_fields())
+
     if crate.has_clock:
         lines.append("    set_clk_fn: unsafe extern \"C\" fn(*mut ModuleHandle, u8),")
         lines.append("    clk_state: u8,")
@@ -561,6 +476,8 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
         ),
     ]
 
+    if crate.trace:
+        impl_lines.extend(lib_rs_trace_loads(crate))
     if crate.has_clock:
         impl_lines.append(
             (
@@ -602,6 +519,8 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
     impl_lines.append("                handle,")
     impl_lines.append("                free_fn,")
     impl_lines.append("                eval_fn,")
+    if crate.trace:
+        impl_lines.extend(lib_rs_trace_inits())
     if crate.has_clock:
         impl_lines.append("                set_clk_fn,")
         impl_lines.append("                clk_state: 0,")
@@ -626,6 +545,9 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
     )
     impl_lines.append("")
 
+    if crate.trace:
+        impl_lines.extend(lib_rs_trace_methods())
+
     if crate.has_clock:
         impl_lines.extend(
             [
@@ -642,6 +564,22 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
                 "        self.eval();",
                 "    }",
                 "",
+                (
+                    "    pub fn run_until<F>(&mut self, max_cycles: usize, mut pred: F)"
+                    " -> Option<usize>"
+                ),
+                "    where",
+                "        F: FnMut(&mut Self) -> bool,",
+                "    {",
+                "        for cycle in 0..max_cycles {",
+                "            if pred(self) {",
+                "                return Some(cycle);",
+                "            }",
+                "            self.clock_tick();",
+                "        }",
+                "        pred(self).then_some(max_cycles)",
+                "    }",
+                "",
             ]
         )
     if crate.has_reset:
@@ -713,13 +651,31 @@ def _generate_lib_rs(crate: ExternalFFIModule) -> str:  # pylint: disable=too-ma
     return "\n".join(lines)
 
 
+def _wrapper_init_line(crate: ExternalFFIModule) -> str:
+    """Return the one-time Verilated runtime setup run by the first `<prefix>_new` call."""
+    setup = ["Verilated::debug(0);"]
+    if crate.trace:
+        setup.append(wrapper_trace_init())
+    if crate.threads > 1:
+        setup.append(f"Verilated::defaultContextp()->threads({crate.threads});")
+    return f"    if (!inited) {{ {' '.join(setup)} inited = true; }}"
+
+
 def _generate_wrapper_cpp(crate: ExternalFFIModule) -> str:
     cpp_class = f"V{crate.top_module}"
     prefix = crate.symbol_prefix
     lines = [
         f"#include \"{cpp_class}.h\"",
         "#include \"verilated.h\"",
+        *(["#include \"verilated_vcd_c.h\""] if crate.trace else []),
         "#include <cstdint>",
+        *(["#include <unordered_map>"] if crate.trace else []),
+        "",
+        "#if defined(_WIN32)",
+        "#define FFI_EXPORT __declspec(dllexport)",
+        "#else",
+        "#define FFI_EXPORT",
+        "#endif",
         "",
         "double sc_time_stamp() { return 0.0; }",
         "",
@@ -727,20 +683,27 @@ def _generate_wrapper_cpp(crate: ExternalFFIModule) -> str:
         "",
         f"using ModuleHandle = {cpp_class};",
         "",
-        f"ModuleHandle* {prefix}_new() {{",
-        "    static bool inited = false;",
-        "    if (!inited) { Verilated::debug(0); inited = true; }",
-        "    return new ModuleHandle();",
-        "}",
-        "",
-        f"void {prefix}_free(ModuleHandle* handle) {{ delete handle; }}",
-        "",
-        f"void {prefix}_eval(ModuleHandle* handle) {{ handle->eval(); }}",
     ]
+    if crate.trace:
+        lines.extend(wrapper_trace_cpp(crate, _wrapper_init_line(crate)))
+    else:
+        lines.extend(
+            [
+                f"FFI_EXPORT ModuleHandle* {prefix}_new() {{",
+                "    static bool inited = false;",
+                _wrapper_init_line(crate),
+                "    return new ModuleHandle();",
+                "}",
+                "",
+                f"FFI_EXPORT void {prefix}_free(ModuleHandle* handle) {{ delete handle; }}",
+                "",
+                f"FFI_EXPORT void {prefix}_eval(ModuleHandle* handle) {{ handle->eval(); }}",
+            ]
+        )
     if crate.has_clock:
         lines.extend(
             [
-                f"void {prefix}_set_clk(ModuleHandle* handle, uint8_t value) {{",
+                f"FFI_EXPORT void {prefix}_set_clk(ModuleHandle* handle, uint8_t value) {{",
                 "    handle->clk = static_cast<uint8_t>(value & 0x1U);",
                 "}",
             ]
@@ -748,7 +711,7 @@ def _generate_wrapper_cpp(crate: ExternalFFIModule) -> str:
     if crate.has_reset:
         lines.extend(
             [
-                f"void {prefix}_set_rst(ModuleHandle* handle, uint8_t value) {{",
+                f"FFI_EXPORT void {prefix}_set_rst(ModuleHandle* handle, uint8_t value) {{",
                 "    handle->rst = static_cast<uint8_t>(value & 0x1U);",
                 "}",
             ]
@@ -756,7 +719,10 @@ def _generate_wrapper_cpp(crate: ExternalFFIModule) -> str:
     for port in crate.inputs:
         lines.extend(
             [
-                f"void {prefix}_set_{port.name}(ModuleHandle* handle, {port.c_type} value) {{",
+                (
+                    f"FFI_EXPORT void {prefix}_set_{port.name}(ModuleHandle* handle, "
+                    f"{port.c_type} value) {{"
+                ),
                 f"    handle->{port.name} = static_cast<{port.c_type}>(value);",
                 "}",
             ]
@@ -764,7 +730,7 @@ def _generate_wrapper_cpp(crate: ExternalFFIModule) -> str:
     for port in crate.outputs:
         lines.extend(
             [
-                f"{port.c_type} {prefix}_get_{port.name}(ModuleHandle* handle) {{",
+                f"FFI_EXPORT {port.c_type} {prefix}_get_{port.name}(ModuleHandle* handle) {{",
                 f"    return static_cast<{port.c_type}>(handle->{port.name});",
                 "}",
             ]
@@ -773,24 +739,76 @@ def _generate_wrapper_cpp(crate: ExternalFFIModule) -> str:
     return "\n".join(lines) + "\n"
 
 
+def _verilator_cache_entry(crate: ExternalFFIModule, sv_source: Path) -> Optional[Path]:
+    """Return where the library built from these exact inputs is cached, if caching is on."""
+    cache_root = verilator_cache_root()
+    if cache_root is None:
+        return None
+    digest = hashlib.sha256()
+    digest.update(sv_source.read_bytes())
+    digest.update((crate.crate_path / "src" / "wrapper.cpp").read_bytes())
+    # Paths inside the workspace are normalized so a regenerated crate still hits.
+    verilator_cmd = verilator_command(crate, Path("<sv>"), Path("<obj_dir>"))
+    compile_cmd, output = compile_command(
+        crate, [Path("<sources>")], resolve_verilator_paths(), Path("<obj_dir>"), shared=True
+    )
+    fingerprint = [
+        *verilator_cmd,
+        verilator_version(resolve_verilator_executable(verilator_cmd[0])),
+        *(
+            arg.replace(str(output), "<output>").replace(str(crate.crate_path), "<crate>")
+            for arg in compile_cmd
+        ),
+        os.environ.get("VERILATOR_ROOT", ""),
+        platform.system(),
+        platform.machine(),
+    ]
+    digest.update("\0".join(fingerprint).encode("utf-8"))
+    return cache_root / digest.hexdigest() / f"model{dynamic_lib_suffix()}"
+
+
+def _store_in_cache(lib_path: Path, cache_entry: Path) -> None:
+    """Copy a freshly built library into the cache without exposing partial files."""
+    cache_entry.parent.mkdir(parents=True, exist_ok=True)
+    staging = cache_entry.with_name(f"{cache_entry.name}.{os.getpid()}.tmp")
+    shutil.copy2(lib_path, staging)
+    os.replace(staging, cache_entry)
+
+
 def _build_verilator_library(crate: ExternalFFIModule) -> Path:
     """Compile the Verilator-generated model and wrapper into a shared library."""
 
     sv_source = _ensure_sv_source(crate)
-    obj_dir = _prepare_build_directory(crate)
-    _run_verilator_compile(crate, sv_source, obj_dir)
-    include_dir, vltstd_dir = _resolve_verilator_paths()
-    source_files = _gather_source_files(crate, obj_dir, include_dir)
-    compile_cmd, lib_filename, lib_path = _build_compile_command(
-        crate,
-        source_files,
-        include_dir,
-        vltstd_dir,
-        obj_dir,
-    )
-    _run_subprocess(compile_cmd)
+    cache_entry = _verilator_cache_entry(crate, sv_source)
+    lib_path = library_path(crate)
+    keep_root = keep_artifacts_root()
+
+    # A cache hit never runs Verilator, so bypass it when the sources are needed.
+    needs_sources = keep_root is not None or crate.standalone
+    if not needs_sources and cache_entry is not None and cache_entry.exists():
+        shutil.copy2(cache_entry, lib_path)
+    else:
+        obj_dir = _prepare_build_directory(crate)
+        run_verilator_compile(crate, sv_source, obj_dir)
+        if crate.aggregate:
+            write_aggregated_source(crate, obj_dir)
+        if keep_root is not None:
+            keep_build_artifacts(crate, sv_source, obj_dir, keep_root)
+        include_dirs = resolve_verilator_paths()
+        source_files = gather_source_files(crate, obj_dir, include_dirs[0])
+        compile_cmd, _ = compile_command(crate, source_files, include_dirs, obj_dir, shared=True)
+        _run_subprocess(compile_cmd)
+        if cache_entry is not None:
+            _store_in_cache(lib_path, cache_entry)
+        if crate.standalone:
+            sim_main = crate.crate_path / "src" / "sim_main.cpp"
+            standalone_cmd, exe_path = compile_command(
+                crate, [*source_files, sim_main], include_dirs, obj_dir, shared=False
+            )
+            _run_subprocess(standalone_cmd)
+            print(f"Built standalone simulator for {crate.crate_name}: {exe_path.resolve()}")
 
-    crate.lib_filename = lib_filename
+    crate.lib_filename = lib_path.name
     crate.lib_path = lib_path
 
     _write_file(crate.crate_path / ".verilator-lib-path", str(lib_path.resolve()))
@@ -802,6 +820,8 @@ def _emit_crate_artifacts(spec: ExternalFFIModule) -> None:
     _write_file(spec.crate_path / "Cargo.toml", _generate_cargo_toml(spec))
     _write_file(spec.crate_path / "src/lib.rs", _generate_lib_rs(spec))
     _write_file(spec.crate_path / "src/wrapper.cpp", _generate_wrapper_cpp(spec))
+    if spec.standalone:
+        _write_file(spec.crate_path / "src/sim_main.cpp", generate_sim_main_cpp(spec))
     _build_verilator_library(spec)
 
 
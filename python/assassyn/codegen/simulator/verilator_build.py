@@ -0,0 +1,479 @@
+"""Native build helpers for the Verilator FFI crates.
+
+Environment-driven build options, the Verilator invocation, host C++ toolchain
+detection, compile commands for the shared library and the optional standalone
+simulator, and the standalone `sim_main.cpp` generator.
+"""
+
+from __future__ import annotations
+
+import os
+import platform
+import re
+import shlex
+import shutil
+import subprocess
+import sys
+import typing
+import warnings
+from pathlib import Path, PureWindowsPath
+from typing import List, Optional, Sequence, Tuple
+
+from ...utils import repo_path
+
+if typing.TYPE_CHECKING:
+    from .verilator import ExternalFFIModule
+
+
+def dynamic_lib_suffix() -> str:
+    """Return the shared-library file suffix of the host platform."""
+    system = platform.system().lower()
+    if system == "windows":
+        return ".dll"
+    if system == "darwin":
+        return ".dylib"
+    return ".so"
+
+
+def split_command_line(raw: str) -> List[str]:
+    """Tokenize a command line from the environment without mangling Windows paths.
+
+    A value naming an existing file is one token even if it contains spaces. On
+    Windows, backslashes are path separators rather than escapes.
+    """
+    raw = raw.strip()
+    if raw and Path(raw).is_file():
+        return [raw]
+    if sys.platform != "win32":
+        return shlex.split(raw)
+    tokens = shlex.split(raw, posix=False)
+    return [tok[1:-1] if len(tok) > 1 and tok[0] == tok[-1] == '"' else tok for tok in tokens]
+
+
+def compiler_command() -> List[str]:
+    """Detect and return the appropriate C++ compiler command."""
+    # First, check if CXX environment variable is set
+    compiler_env = os.environ.get("CXX")
+    if compiler_env:
+        tokens = split_command_line(compiler_env)
+        if tokens:
+            return tokens
+    # Try to detect the system's default C++ compiler more intelligently
+    # Check for common compiler environment variables
+    for env_var in ["CXX", "CC"]:
+        if env_var in os.environ:
+            compiler_path = os.environ[env_var]
+            if compiler_path and shutil.which(compiler_path):
+                return [compiler_path]
+    # Fallback to common C++ compilers, but try to be more system-appropriate
+    candidates = []
+    # On macOS, prefer clang++ if available (it's the default)
+    if sys.platform == "darwin":
+        candidates = ["clang++", "g++", "c++"]
+    # On Linux, prefer c++ (generic) then g++, then clang++
+    elif sys.platform.startswith("linux"):
+        candidates = ["c++", "g++", "clang++"]
+    # On Windows, prefer the MSVC driver, then its clang front-end, then MinGW
+    elif sys.platform == "win32":
+        candidates = ["cl", "clang-cl", "g++", "clang++"]
+    # On other systems, use a generic order
+    else:
+        candidates = ["c++", "g++", "clang++"]
+    for candidate in candidates:
+        path = shutil.which(candidate)
+        if path:
+            return [path]
+    raise RuntimeError(
+        "Unable to locate a C++ compiler. Please set the CXX environment variable "
+        "or install a C++ compiler (g++, clang++, or c++)."
+    )
+
+
+def is_msvc_compiler(compiler: List[str]) -> bool:
+    """Return True if the compiler driver takes MSVC-style (`cl.exe`) arguments."""
+    name = PureWindowsPath(compiler[0]).name.lower()
+    if name.endswith(".exe"):
+        name = name[:-len(".exe")]
+    return name in ("cl", "clang-cl")
+
+
+def library_path(crate: ExternalFFIModule) -> Path:
+    """Return where the crate's shared library is built."""
+    return crate.crate_path / f"lib{crate.dynamic_lib_name}{dynamic_lib_suffix()}"
+
+
+def standalone_path(crate: ExternalFFIModule) -> Path:
+    """Return where the crate's standalone simulator executable is built."""
+    exe_suffix = ".exe" if platform.system().lower() == "windows" else ""
+    return crate.crate_path / f"sim_{crate.top_module}{exe_suffix}"
+
+
+def compile_command(
+    crate: ExternalFFIModule,
+    source_files: Sequence[Path],
+    include_dirs: Sequence[Path],
+    obj_dir: Path,
+    shared: bool,
+) -> Tuple[List[str], Path]:
+    """Construct the host compiler command for the shared library or the standalone binary.
+
+    `obj_dir` is appended to `include_dirs`; MSVC also writes its objects there.
+    Returns the command and the path of the file it produces.
+    """
+    compiler = compiler_command()
+    output = library_path(crate) if shared else standalone_path(crate)
+    includes = [*include_dirs, obj_dir]
+
+    if is_msvc_compiler(compiler):
+        compile_cmd = compiler + ["/nologo", "/std:c++17", "/EHsc", "/O2", "/MD"]
+        if shared:
+            compile_cmd.append("/LD")
+        compile_cmd.append(f"/Fo{obj_dir}{os.sep}")
+        compile_cmd.extend(f"/I{include}" for include in includes)
+        compile_cmd.extend(str(src) for src in source_files)
+        compile_cmd.append("/link")
+        if shared:
+            compile_cmd.append("/DLL")
+        compile_cmd.append(f"/OUT:{output}")
+        return compile_cmd, output
+
+    compile_cmd = compiler + ["-std=c++17"]
+    if shared:
+        compile_cmd.extend(["-shared", "-fPIC"])
+    compile_cmd.append("-O3")
+    if crate.threads > 1:
+        compile_cmd.append("-pthread")
+    for include in includes:
+        compile_cmd.extend(["-I", str(include)])
+    compile_cmd.extend(str(src) for src in source_files)
+    compile_cmd.extend(["-o", str(output)])
+    return compile_cmd, output
+
+
+def keep_artifacts_root() -> Optional[Path]:
+    """Return the ASSASSYN_KEEP_BUILD_ARTIFACTS directory, or None when unset."""
+    value = os.environ.get("ASSASSYN_KEEP_BUILD_ARTIFACTS", "").strip()
+    return Path(value) if value else None
+
+
+def keep_build_artifacts(
+    crate: ExternalFFIModule,
+    sv_source: Path,
+    obj_dir: Path,
+    keep_root: Path,
+) -> Path:
+    """Copy the SV source, verilated sources, and wrapper into *keep_root* for inspection."""
+    dest = keep_root / crate.crate_name
+    shutil.rmtree(dest, ignore_errors=True)
+    dest.mkdir(parents=True)
+    shutil.copy2(sv_source, dest / sv_source.name)
+    shutil.copy2(crate.crate_path / "src" / "wrapper.cpp", dest / "wrapper.cpp")
+    shutil.copytree(obj_dir, dest / "verilated")
+    print(f"Kept Verilator build artifacts for {crate.crate_name} in {dest.resolve()}")
+    return dest
+
+
+_FALSE_LIKE = ("", "0", "false", "no", "off")
+_TRUE_LIKE = ("1", "true", "yes", "on")
+
+
+def _env_flag(name: str) -> bool:
+    """Return True if environment variable *name* is set to a non-false-like value."""
+    return os.environ.get(name, "").strip().lower() not in _FALSE_LIKE
+
+
+def trace_enabled() -> bool:
+    """Return True if ASSASSYN_VERILATOR_TRACE requests VCD waveform support."""
+    return _env_flag("ASSASSYN_VERILATOR_TRACE")
+
+
+def aggregate_enabled() -> bool:
+    """Return True if ASSASSYN_VERILATOR_AGGREGATE requests a single translation unit."""
+    return _env_flag("ASSASSYN_VERILATOR_AGGREGATE")
+
+
+def standalone_enabled() -> bool:
+    """Return True if ASSASSYN_VERILATOR_STANDALONE requests a C++-only simulator binary."""
+    return _env_flag("ASSASSYN_VERILATOR_STANDALONE")
+
+
+def verilator_cache_root() -> Optional[Path]:
+    """Return the opt-in shared library cache directory, or None if caching is off."""
+    if not _env_flag("ASSASSYN_VERILATOR_CACHE"):
+        return None
+    configured = os.environ["ASSASSYN_VERILATOR_CACHE"].strip()
+    if configured.lower() in _TRUE_LIKE:
+        return Path(repo_path()) / ".verilator-cache"
+    return Path(configured)
+
+
+def verilator_threads() -> int:
+    """Return the model thread count from ASSASSYN_VERILATOR_THREADS, defaulting to 1."""
+    try:
+        threads = int(os.environ.get("ASSASSYN_VERILATOR_THREADS", "1"))
+    except ValueError:
+        return 1
+    return max(threads, 1)
+
+
+def _extra_verilator_flags() -> List[str]:
+    """Return user flags from ASSASSYN_VERILATOR_FLAGS, ignoring empty or malformed values."""
+    raw = os.environ.get("ASSASSYN_VERILATOR_FLAGS", "")
+    try:
+        return split_command_line(raw)
+    except ValueError:
+        return []
+
+
+def _top_module_args(crate: ExternalFFIModule) -> List[str]:
+    """Return the `--top-module` arguments, honoring an ASSASSYN_TOP_MODULE override."""
+    override = os.environ.get("ASSASSYN_TOP_MODULE", "").strip()
+    if not override:
+        return ["--top-module", crate.top_module]
+    if not re.fullmatch(r"[A-Za-z_][A-Za-z0-9_$]*", override):
+        raise ValueError(
+            f"ASSASSYN_TOP_MODULE must be a SystemVerilog identifier, got '{override}'"
+        )
+    # Keep the generated class named after the original top so wrapper.cpp still compiles.
+    return ["--top-module", override, "--prefix", f"V{crate.top_module}"]
+
+
+def verilator_command(crate: ExternalFFIModule, sv_source: Path, obj_dir: Path) -> List[str]:
+    """Assemble the Verilator invocation that generates the C++ model."""
+    verilator_exe = os.environ.get("ASSASSYN_VERILATOR", "verilator")
+    return [
+        verilator_exe,
+        "--cc",
+        str(sv_source),
+        *_top_module_args(crate),
+        "-O3",
+        *(["--trace"] if crate.trace else []),
+        *(["--threads", str(crate.threads)] if crate.threads > 1 else []),
+        *(["--output-split", "0"] if crate.aggregate else []),
+        *_extra_verilator_flags(),
+        "--Mdir",
+        str(obj_dir),
+    ]
+
+
+def resolve_verilator_executable(requested: str) -> str:
+    """Resolve the Verilator executable against PATH, failing with setup guidance."""
+    resolved = shutil.which(requested)
+    if resolved is None:
+        raise FileNotFoundError(
+            f"Verilator executable '{requested}' was not found. Install Verilator, point "
+            "ASSASSYN_VERILATOR at the binary, or run 'source setup.sh' so that "
+            "$VERILATOR_ROOT/bin is on PATH."
+        )
+    return resolved
+
+
+def verilator_version(executable: str) -> str:
+    """Return the `--version` banner of the resolved Verilator *executable*."""
+    result = subprocess.run(
+        [executable, "--version"],
+        check=False,
+        capture_output=True,
+        text=True,
+        env=os.environ.copy(),
+    )
+    return result.stdout.strip()
+
+
+def run_verilator_compile(crate: ExternalFFIModule, sv_source: Path, obj_dir: Path) -> None:
+    """Invoke Verilator to generate the C++ model."""
+    verilator_cmd = verilator_command(crate, sv_source, obj_dir)
+    verilator_cmd[0] = resolve_verilator_executable(verilator_cmd[0])
+    result = subprocess.run(
+        verilator_cmd,
+        check=False,
+        capture_output=True,
+        text=True,
+        env=os.environ.copy(),
+    )
+    # stdout carries the Verilation report and is echoed as if it were not captured.
+    sys.stdout.write(result.stdout)
+    if result.returncode != 0:
+        raise RuntimeError(
+            f"Verilator failed on '{sv_source}' (exit code {result.returncode}):\n"
+            f"{result.stderr.strip()}"
+        )
+    remaining = _emit_verilator_warnings(result.stderr)
+    if remaining:
+        sys.stderr.write(remaining)
+
+
+def _emit_verilator_warnings(output: str) -> str:
+    """Re-raise Verilator `%Warning` diagnostics as Python warnings.
+
+    Continuation lines (indented or starting with `:`) stay attached to their
+    warning. Everything else is returned so the caller can still echo it.
+    """
+    diagnostics: List[List[str]] = []
+    others: List[str] = []
+    current: Optional[List[str]] = None
+    for line in output.splitlines():
+        if line.startswith("%Warning"):
+            current = [line]
+            diagnostics.append(current)
+        elif current is not None and line[:1] in (" ", "\t", ":"):
+            current.append(line)
+        else:
+            current = None
+            others.append(line)
+    for diagnostic in diagnostics:
+        warnings.warn("\n".join(diagnostic), UserWarning, stacklevel=3)
+    return "".join(f"{line}\n" for line in others)
+
+
+def resolve_verilator_paths() -> tuple[Path, Path]:
+    """Locate the Verilator include directories."""
+    verilator_root = os.environ.get("VERILATOR_ROOT")
+    if not verilator_root:
+        raise EnvironmentError(
+            "VERILATOR_ROOT is not set. Please run 'source setup.sh' before "
+            "generating external FFIs."
+        )
+    include_dir = Path(verilator_root) / "include"
+    if not include_dir.exists():
+        raise FileNotFoundError(f"Verilator include directory not found: {include_dir}")
+    return include_dir, include_dir / "vltstd"
+
+
+def write_aggregated_source(crate: ExternalFFIModule, obj_dir: Path) -> Path:
+    """Write `V<top>__ALL.cpp`, a single translation unit including every generated source."""
+    aggregated = obj_dir / f"V{crate.top_module}__ALL.cpp"
+    includes = [
+        f"#include \"{path.name}\""
+        for path in sorted(obj_dir.glob("*.cpp"))
+        if not path.name.endswith("__ALL.cpp")
+    ]
+    aggregated.write_text("\n".join(includes) + "\n", encoding="utf-8")
+    return aggregated
+
+
+def gather_source_files(
+    crate: ExternalFFIModule,
+    obj_dir: Path,
+    include_dir: Path,
+) -> List[Path]:
+    """Collect all C++ sources required to build the shared library."""
+    cpp_class = f"V{crate.top_module}"
+    aggregated = obj_dir / f"{cpp_class}__ALL.cpp"
+
+    source_files: List[Path] = []
+    if aggregated.exists():
+        source_files.append(aggregated)
+    else:
+        for path in sorted(obj_dir.glob("*.cpp")):
+            if path.name.endswith("__ALL.cpp"):
+                continue
+            source_files.append(path)
+
+    wrapper_src = crate.crate_path / "src" / "wrapper.cpp"
+    if not wrapper_src.exists():
+        raise FileNotFoundError(f"Wrapper source not found: {wrapper_src}")
+    source_files.append(wrapper_src)
+
+    runtime_sources = [include_dir / "verilated.cpp"]
+    if crate.trace:
+        runtime_sources.append(include_dir / "verilated_vcd_c.cpp")
+    for extra in ("verilated_threads.cpp", "verilated_dpi.cpp"):
+        extra_path = include_dir / extra
+        if extra_path.exists():
+            runtime_sources.append(extra_path)
+    source_files.extend(runtime_sources)
+    return source_files
+
+
+def generate_sim_main_cpp(crate: ExternalFFIModule) -> str:
+    """Emit a standalone `main()` that drives the model through the wrapper's C ABI.
+
+    A module with a reset is held in reset for one tick and then ticked once
+    more after release, like `apply_reset` in the Rust wrapper; neither tick is
+    printed. The binary then steps `argv[1]` cycles (default 10) and prints
+    every output port after each one.
+    """
+    cpp_class = f"V{crate.top_module}"
+    prefix = crate.symbol_prefix
+    lines = [
+        f"#include \"{cpp_class}.h\"",
+        "#include <cstdint>",
+        "#include <cstdio>",
+        "#include <cstdlib>",
+        "",
+        "extern \"C\" {",
+        f"using ModuleHandle = {cpp_class};",
+        f"ModuleHandle* {prefix}_new();",
+        f"void {prefix}_free(ModuleHandle* handle);",
+        f"void {prefix}_eval(ModuleHandle* handle);",
+    ]
+    if crate.has_clock:
+        lines.append(f"void {prefix}_set_clk(ModuleHandle* handle, uint8_t value);")
+    if crate.has_reset:
+        lines.append(f"void {prefix}_set_rst(ModuleHandle* handle, uint8_t value);")
+    for port in crate.outputs:
+        lines.append(f"{port.c_type} {prefix}_get_{port.name}(ModuleHandle* handle);")
+    lines.extend(
+        [
+            "}",
+            "",
+            "static void tick(ModuleHandle* model) {",
+        ]
+    )
+    if crate.has_clock:
+        lines.extend(
+            [
+                f"    {prefix}_set_clk(model, 0);",
+                f"    {prefix}_eval(model);",
+                f"    {prefix}_set_clk(model, 1);",
+            ]
+        )
+    lines.extend(
+        [
+            f"    {prefix}_eval(model);",
+            "}",
+            "",
+            "int main(int argc, char** argv) {",
+            "    unsigned long long cycles = argc > 1 ? std::strtoull(argv[1], nullptr, 10) : 10;",
+            f"    ModuleHandle* model = {prefix}_new();",
+        ]
+    )
+    if crate.has_reset:
+        lines.extend(
+            [
+                f"    {prefix}_set_rst(model, 1);",
+                "    tick(model);",
+                f"    {prefix}_set_rst(model, 0);",
+                "    tick(model);",
+            ]
+        )
+    lines.extend(
+        [
+            "    for (unsigned long long cycle = 0; cycle < cycles; ++cycle) {",
+            "        tick(model);",
+            "        std::printf(\"cycle %llu\", cycle);",
+        ]
+    )
+    for port in crate.outputs:
+        value = f"{prefix}_get_{port.name}(model)"
+        if port.signed:
+            lines.append(
+                f"        std::printf(\" {port.name}=%lld\", static_cast<long long>({value}));"
+            )
+        else:
+            lines.append(
+                f"        std::printf(\" {port.name}=%llu\", "
+                f"static_cast<unsigned long long>({value}));"
+            )
+    lines.extend(
+        [
+            "        std::printf(\"\\n\");",
+            "    }",
+            f"    {prefix}_free(model);",
+            "    return 0;",
+            "}",
+            "",
+        ]
+    )
+    return "\n".join(lines)
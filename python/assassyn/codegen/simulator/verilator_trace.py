@@ -0,0 +1,122 @@
+"""VCD tracing support for the Verilator FFI crates."""
+
+from __future__ import annotations
+
+import typing
+from typing import List
+
+if typing.TYPE_CHECKING:
+    from .verilator import ExternalFFIModule
+
+_TRACE_FNS = ("trace_open", "trace_flush", "trace_close")
+
+
+def wrapper_trace_init() -> str:
+    """Return the Verilated runtime setup a traced wrapper adds to its one-time init."""
+    return "Verilated::traceEverOn(true);"
+
+
+def wrapper_trace_cpp(crate: ExternalFFIModule, init_line: str) -> List[str]:
+    """Emit the lifecycle entry points of a wrapper that can dump a VCD per handle."""
+    prefix = crate.symbol_prefix
+    return [
+        "struct TraceState {",
+        "    VerilatedVcdC* vcd;",
+        "    uint64_t time;",
+        "};",
+        "",
+        "static std::unordered_map<ModuleHandle*, TraceState> traces;",
+        "",
+        "static void close_trace(ModuleHandle* handle) {",
+        "    auto it = traces.find(handle);",
+        "    if (it == traces.end()) { return; }",
+        "    it->second.vcd->close();",
+        "    delete it->second.vcd;",
+        "    traces.erase(it);",
+        "}",
+        "",
+        f"FFI_EXPORT ModuleHandle* {prefix}_new() {{",
+        "    static bool inited = false;",
+        init_line,
+        "    return new ModuleHandle();",
+        "}",
+        "",
+        f"FFI_EXPORT void {prefix}_free(ModuleHandle* handle) {{",
+        "    close_trace(handle);",
+        "    delete handle;",
+        "}",
+        "",
+        f"FFI_EXPORT void {prefix}_eval(ModuleHandle* handle) {{",
+        "    handle->eval();",
+        "    auto it = traces.find(handle);",
+        "    if (it != traces.end()) { it->second.vcd->dump(it->second.time++); }",
+        "}",
+        "",
+        f"FFI_EXPORT void {prefix}_trace_open(ModuleHandle* handle, const char* path) {{",
+        "    close_trace(handle);",
+        "    auto* vcd = new VerilatedVcdC();",
+        "    handle->trace(vcd, 99);",
+        "    vcd->open(path);",
+        "    traces[handle] = TraceState{vcd, 0};",
+        "}",
+        "",
+        f"FFI_EXPORT void {prefix}_trace_flush(ModuleHandle* handle) {{",
+        "    auto it = traces.find(handle);",
+        "    if (it != traces.end()) { it->second.vcd->flush(); }",
+        "}",
+        "",
+        f"FFI_EXPORT void {prefix}_trace_close(ModuleHandle* handle) {{ close_trace(handle); }}",
+    ]
+
+
+def _trace_fn_type(name: str) -> str:
+    """Return the Rust function-pointer type of a trace entry point."""
+    if name == "trace_open":
+        return "unsafe extern \"C\" fn(*mut ModuleHandle, *const std::ffi::c_char)"
+    return "unsafe extern \"C\" fn(*mut ModuleHandle)"
+
+
+def lib_rs_trace_fields() -> List[str]:
+    """Struct fields holding the trace entry points."""
+    return [f"    {name}_fn: {_trace_fn_type(name)}," for name in _TRACE_FNS]
+
+
+def lib_rs_trace_loads(crate: ExternalFFIModule) -> List[str]:
+    """Symbol loads for the trace entry points inside `new_from_path`."""
+    prefix = crate.symbol_prefix
+    return [
+        (
+            f"            let {name}_fn: {_trace_fn_type(name)} = "
+            f"load_symbol(&lib, b\"{prefix}_{name}\", \"{prefix}_{name}\");"
+        )
+        for name in _TRACE_FNS
+    ]
+
+
+def lib_rs_trace_inits() -> List[str]:
+    """Struct initializer entries for the trace entry points."""
+    return [f"                {name}_fn," for name in _TRACE_FNS]
+
+
+def lib_rs_trace_methods() -> List[str]:
+    """Public `open_trace` / `flush_trace` / `close_trace` methods."""
+    return [
+        "    pub fn open_trace<P: AsRef<Path>>(&mut self, path: P) {",
+        "        let path = path.as_ref();",
+        (
+            "        let c_path = CString::new(path.to_string_lossy().into_owned())"
+            ".unwrap_or_else(|err| panic!(\"invalid trace path {}: {err}\", "
+            "path.display()));"
+        ),
+        "        unsafe { (self.trace_open_fn)(self.handle.as_ptr(), c_path.as_ptr()) };",
+        "    }",
+        "",
+        "    pub fn flush_trace(&mut self) {",
+        "        unsafe { (self.trace_flush_fn)(self.handle.as_ptr()) };",
+        "    }",
+        "",
+        "    pub fn close_trace(&mut self) {",
+        "        unsafe { (self.trace_close_fn)(self.handle.as_ptr()) };",
+        "    }",
+        "",
+    ]
@@ -0,0 +1,493 @@
+"""Unit tests for the Verilator FFI build helpers."""
+# pylint: disable=protected-access
+
+import shutil
+import subprocess
+import warnings
+from pathlib import Path
+from types import SimpleNamespace
+
+import pytest
+
+from assassyn.codegen.simulator import verilator, verilator_build
+from assassyn.codegen.simulator.verilator import ExternalFFIModule
+from assassyn.ir.dtype import UInt
+
+
+def _make_spec(tmp_path: Path) -> ExternalFFIModule:
+    """Create a minimal spec rooted at *tmp_path*."""
+    return ExternalFFIModule(
+        crate_name="verilated_adder",
+        crate_path=tmp_path,
+        symbol_prefix="verilated_adder",
+        dynamic_lib_name="verilated_adder_ffi",
+        top_module="adder",
+        sv_filename="adder.sv",
+        sv_rel_path="rtl/adder.sv",
+    )
+
+
+def _compile_command(spec: ExternalFFIModule, tmp_path: Path, shared: bool = True):
+    """Assemble the host build command for a single dummy source."""
+    return verilator_build.compile_command(
+        spec, [tmp_path / "a.cpp"], [tmp_path / "inc", tmp_path / "vltstd"], tmp_path / "obj",
+        shared=shared,
+    )
+
+
+def test_gnu_compile_command(tmp_path, monkeypatch):
+    """GCC/Clang drivers build a position-independent shared object or a plain executable."""
+    monkeypatch.setattr(verilator_build, "compiler_command", lambda: ["/usr/bin/g++"])
+    spec = _make_spec(tmp_path)
+    cmd, lib_path = _compile_command(spec, tmp_path)
+    assert lib_path == verilator_build.library_path(spec)
+    assert "-shared" in cmd and "-fPIC" in cmd
+    assert cmd[-2:] == ["-o", str(lib_path)]
+
+    cmd, exe_path = _compile_command(spec, tmp_path, shared=False)
+    assert exe_path.name == "sim_adder"
+    assert "-shared" not in cmd and "-fPIC" not in cmd
+    assert cmd[-2:] == ["-o", str(exe_path)]
+
+
+def test_msvc_compile_command(tmp_path, monkeypatch):
+    """MSVC drivers get /LD and /DLL only for the shared library."""
+    monkeypatch.setattr(verilator_build, "compiler_command", lambda: ["C:\\VS\\bin\\CL.EXE"])
+    monkeypatch.setattr(verilator_build, "dynamic_lib_suffix", lambda: ".dll")
+    spec = _make_spec(tmp_path)
+    cmd, lib_path = _compile_command(spec, tmp_path)
+    assert lib_path.name == "libverilated_adder_ffi.dll"
+    assert "/LD" in cmd
+    assert "-shared" not in cmd and "-fPIC" not in cmd
+    assert f"/I{tmp_path / 'inc'}" in cmd and f"/I{tmp_path / 'obj'}" in cmd
+    assert cmd[-3:] == ["/link", "/DLL", f"/OUT:{lib_path}"]
+
+    cmd, exe_path = _compile_command(spec, tmp_path, shared=False)
+    assert "/LD" not in cmd and "/DLL" not in cmd
+    assert cmd[-2:] == ["/link", f"/OUT:{exe_path}"]
+
+
+def test_environment_commands_keep_windows_paths(tmp_path, monkeypatch):
+    """CXX and ASSASSYN_VERILATOR_FLAGS keep backslash paths, so MSVC is still detected."""
+    monkeypatch.setattr(verilator_build.sys, "platform", "win32")
+    monkeypatch.setenv("CXX", "C:\\VS\\bin\\cl.exe")
+    assert verilator_build.compiler_command() == ["C:\\VS\\bin\\cl.exe"]
+    assert verilator_build.is_msvc_compiler(verilator_build.compiler_command())
+    monkeypatch.setenv("CXX", '"C:\\Program Files\\VS\\cl.exe" /nologo')
+    assert verilator_build.compiler_command() == ["C:\\Program Files\\VS\\cl.exe", "/nologo"]
+    monkeypatch.setenv("ASSASSYN_VERILATOR_FLAGS", "-IC:\\rtl\\inc -Wall")
+    cmd = verilator_build.verilator_command(_make_spec(tmp_path), tmp_path / "a.sv", tmp_path)
+    assert "-IC:\\rtl\\inc" in cmd and "-Wall" in cmd
+
+    monkeypatch.setattr(verilator_build.sys, "platform", "linux")
+    compiler = tmp_path / "my tools" / "g++"
+    compiler.parent.mkdir()
+    compiler.write_text("")
+    monkeypatch.setenv("CXX", str(compiler))
+    assert verilator_build.compiler_command() == [str(compiler)]
+
+
+def test_wrapper_exports_symbols(tmp_path):
+    """Every C ABI entry point is marked for export so Windows DLLs expose it."""
+    spec = _make_spec(tmp_path)
+    wrapper = verilator._generate_wrapper_cpp(spec)
+    assert "__declspec(dllexport)" in wrapper
+    for symbol in ("_new()", "_free(", "_eval("):
+        line = next(l for l in wrapper.splitlines() if f"verilated_adder{symbol}" in l)
+        assert line.startswith("FFI_EXPORT ")
+
+
+def test_extra_verilator_flags_spliced_before_mdir(tmp_path, monkeypatch):
+    """ASSASSYN_VERILATOR_FLAGS lands after the fixed options and before --Mdir."""
+    monkeypatch.setenv("ASSASSYN_VERILATOR_FLAGS", '-Wall -GWIDTH=8 "+define+NAME=a b"')
+    spec = _make_spec(tmp_path)
+    cmd = verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    mdir = cmd.index("--Mdir")
+    assert cmd[mdir - 3:mdir] == ["-Wall", "-GWIDTH=8", "+define+NAME=a b"]
+    assert cmd[mdir - 4] == "-O3"
+    assert cmd[mdir + 1] == str(tmp_path / "obj")
+
+
+def test_extra_verilator_flags_ignore_bad_values(tmp_path, monkeypatch):
+    """Empty or unparsable flag strings leave the command untouched."""
+    spec = _make_spec(tmp_path)
+    sv_source, obj_dir = tmp_path / "adder.sv", tmp_path / "obj"
+    monkeypatch.delenv("ASSASSYN_VERILATOR_FLAGS", raising=False)
+    baseline = verilator_build.verilator_command(spec, sv_source, obj_dir)
+    for value in ("", "   ", '--trace "unterminated'):
+        monkeypatch.setenv("ASSASSYN_VERILATOR_FLAGS", value)
+        assert verilator_build.verilator_command(spec, sv_source, obj_dir) == baseline
+
+
+def test_trace_disabled_by_default(tmp_path, monkeypatch):
+    """Without ASSASSYN_VERILATOR_TRACE the build stays free of tracing support."""
+    monkeypatch.delenv("ASSASSYN_VERILATOR_TRACE", raising=False)
+    assert not verilator_build.trace_enabled()
+    spec = _make_spec(tmp_path)
+    assert "--trace" not in verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path)
+    assert "VerilatedVcdC" not in verilator._generate_wrapper_cpp(spec)
+    assert "open_trace" not in verilator._generate_lib_rs(spec)
+
+
+def test_trace_enabled_wires_vcd_hooks(tmp_path, monkeypatch):
+    """A traced spec passes --trace, links the VCD writer, and exposes dump controls."""
+    monkeypatch.setenv("ASSASSYN_VERILATOR_TRACE", "1")
+    assert verilator_build.trace_enabled()
+    spec = _make_spec(tmp_path)
+    spec.trace = True
+
+    cmd = verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    assert "--trace" in cmd[:cmd.index("--Mdir")]
+
+    include_dir = tmp_path / "include"
+    obj_dir = tmp_path / "obj"
+    obj_dir.mkdir()
+    (tmp_path / "src").mkdir()
+    (tmp_path / "src" / "wrapper.cpp").write_text("")
+    sources = verilator_build.gather_source_files(spec, obj_dir, include_dir)
+    assert include_dir / "verilated_vcd_c.cpp" in sources
+
+    wrapper = verilator._generate_wrapper_cpp(spec)
+    assert "Verilated::traceEverOn(true)" in wrapper
+    for symbol in ("trace_open", "trace_flush", "trace_close"):
+        assert f"FFI_EXPORT void verilated_adder_{symbol}(" in wrapper
+
+    lib_rs = verilator._generate_lib_rs(spec)
+    for method in ("open_trace", "flush_trace", "close_trace"):
+        assert f"pub fn {method}" in lib_rs
+
+
+def test_verilator_threads_parsing(monkeypatch):
+    """Only integers above one enable multi-threading; anything else stays single-threaded."""
+    monkeypatch.delenv("ASSASSYN_VERILATOR_THREADS", raising=False)
+    assert verilator_build.verilator_threads() == 1
+    for value, expected in (("4", 4), ("1", 1), ("0", 1), ("-2", 1), ("many", 1)):
+        monkeypatch.setenv("ASSASSYN_VERILATOR_THREADS", value)
+        assert verilator_build.verilator_threads() == expected
+
+
+def test_multithreaded_model_build(tmp_path, monkeypatch):
+    """A threaded spec verilates with --threads, links pthreads, and sizes the pool."""
+    monkeypatch.setattr(verilator_build, "compiler_command", lambda: ["/usr/bin/g++"])
+    spec = _make_spec(tmp_path)
+    cmd = verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path)
+    assert "--threads" not in cmd
+    assert "-pthread" not in _compile_command(spec, tmp_path)[0]
+
+    spec.threads = 4
+    cmd = verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    threads = cmd.index("--threads")
+    assert cmd[threads + 1] == "4" and threads < cmd.index("--Mdir")
+    assert "-pthread" in _compile_command(spec, tmp_path)[0]
+    assert "Verilated::defaultContextp()->threads(4);" in verilator._generate_wrapper_cpp(spec)
+
+
+def _fake_verilator(tmp_path: Path, script: str, version: str = "Verilator 5.030") -> Path:
+    """Write an executable stand-in for Verilator that runs *script*."""
+    exe = tmp_path / "fake-verilator"
+    banner = f'if [ "$1" = --version ]; then echo "{version}"; exit 0; fi'
+    exe.write_text(f"#!/bin/sh\n{banner}\n{script}\n")
+    exe.chmod(0o755)
+    return exe
+
+
+def test_missing_verilator_reports_setup(tmp_path, monkeypatch):
+    """An unresolvable executable fails before running, naming the setup knobs."""
+    monkeypatch.setenv("ASSASSYN_VERILATOR", str(tmp_path / "no-such-verilator"))
+    spec = _make_spec(tmp_path)
+    with pytest.raises(FileNotFoundError) as info:
+        verilator_build.run_verilator_compile(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    message = str(info.value)
+    assert "no-such-verilator" in message
+    assert "ASSASSYN_VERILATOR" in message and "VERILATOR_ROOT" in message
+
+
+def test_verilator_failure_surfaces_stderr(tmp_path, monkeypatch):
+    """A failing Verilator run raises with its own diagnostics attached."""
+    exe = _fake_verilator(tmp_path, "echo '%Error: adder.sv:3: syntax error' >&2\nexit 1")
+    monkeypatch.setenv("ASSASSYN_VERILATOR", str(exe))
+    spec = _make_spec(tmp_path)
+    with pytest.raises(RuntimeError, match="syntax error") as info:
+        verilator_build.run_verilator_compile(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    assert "exit code 1" in str(info.value)
+
+
+def test_verilator_warnings_become_python_warnings(tmp_path, monkeypatch, capsys):
+    """Verilator lint output is re-raised as warnings instead of vanishing."""
+    exe = _fake_verilator(
+        tmp_path,
+        "echo '%Warning-WIDTH: adder.sv:4: Operator ADD expects 32 bits' >&2\n"
+        "echo '                 : ... note: In instance adder' >&2\n"
+        "echo '%Note: unrelated diagnostic' >&2\n"
+        "echo '- V e r i l a t i o n   R e p o r t'",
+    )
+    monkeypatch.setenv("ASSASSYN_VERILATOR", str(exe))
+    spec = _make_spec(tmp_path)
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        verilator_build.run_verilator_compile(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    messages = [str(item.message) for item in caught]
+    assert len(messages) == 1
+    assert messages[0].startswith("%Warning-WIDTH: adder.sv:4")
+    assert "In instance adder" in messages[0]
+    echoed = capsys.readouterr()
+    assert "R e p o r t" in echoed.out
+    assert "%Note: unrelated diagnostic" in echoed.err and "%Warning" not in echoed.err
+
+
+def test_run_until_only_for_clocked_crates(tmp_path):
+    """Clocked wrappers gain a bounded stepping loop with an early-exit predicate."""
+    spec = _make_spec(tmp_path)
+    assert "pub fn run_until" not in verilator._generate_lib_rs(spec)
+
+    spec.has_clock = True
+    lib_rs = verilator._generate_lib_rs(spec)
+    signature = "pub fn run_until<F>(&mut self, max_cycles: usize, mut pred: F) -> Option<usize>"
+    assert signature in lib_rs
+    assert "F: FnMut(&mut Self) -> bool," in lib_rs
+    loop = lib_rs[lib_rs.index("pub fn run_until"):]
+    assert loop.index("if pred(self)") < loop.index("self.clock_tick();")
+
+
+def _prepare_cached_build(tmp_path: Path, monkeypatch, name: str, sv_text: str):
+    """Lay out a crate whose Verilator run is logged and whose compile step is stubbed."""
+    log = tmp_path / "verilator-runs.log"
+    exe = _fake_verilator(tmp_path, f"echo run >> '{log}'")
+    monkeypatch.setenv("ASSASSYN_VERILATOR", str(exe))
+    monkeypatch.setenv("ASSASSYN_VERILATOR_CACHE", str(tmp_path / "cache"))
+    monkeypatch.setattr(verilator_build, "compiler_command", lambda: ["/usr/bin/g++"])
+    monkeypatch.setattr(
+        verilator, "resolve_verilator_paths", lambda: (tmp_path / "inc", tmp_path / "vltstd")
+    )
+    monkeypatch.setattr(
+        verilator, "_run_subprocess", lambda cmd, cwd=None: Path(cmd[-1]).write_text("model")
+    )
+    spec = _make_spec(tmp_path / name)
+    (spec.crate_path / "rtl").mkdir(parents=True)
+    (spec.crate_path / "rtl" / "adder.sv").write_text(sv_text)
+    (spec.crate_path / "src").mkdir()
+    (spec.crate_path / "src" / "wrapper.cpp").write_text(verilator._generate_wrapper_cpp(spec))
+    return spec, log
+
+
+def test_unchanged_sources_skip_verilator(tmp_path, monkeypatch):
+    """A rebuild with identical inputs reuses the cached library instead of re-verilating."""
+    first, log = _prepare_cached_build(tmp_path, monkeypatch, "first", "module adder; endmodule")
+    verilator._build_verilator_library(first)
+    assert log.read_text().count("run") == 1
+
+    second, _ = _prepare_cached_build(tmp_path, monkeypatch, "second", "module adder; endmodule")
+    lib_path = verilator._build_verilator_library(second)
+    assert log.read_text().count("run") == 1
+    assert lib_path.read_text() == "model"
+    assert (second.crate_path / ".verilator-lib-path").read_text() == str(lib_path.resolve())
+
+    new_sv = "module adder(); endmodule"
+    changed, _ = _prepare_cached_build(tmp_path, monkeypatch, "changed", new_sv)
+    verilator._build_verilator_library(changed)
+    assert log.read_text().count("run") == 2
+
+    monkeypatch.setenv("ASSASSYN_VERILATOR_FLAGS", "-Wall")
+    verilator._build_verilator_library(changed)
+    assert log.read_text().count("run") == 3
+
+
+def test_keep_build_artifacts(tmp_path, monkeypatch):
+    """ASSASSYN_KEEP_BUILD_ARTIFACTS snapshots the build inputs, bypassing the cache."""
+    keep = tmp_path / "kept"
+    spec, log = _prepare_cached_build(tmp_path, monkeypatch, "keep", "module adder; endmodule")
+    _fake_verilator(
+        tmp_path,
+        f"echo run >> '{log}'\nfor arg; do mdir=$arg; done\necho '// model' > \"$mdir/Vadder.cpp\"",
+    )
+    monkeypatch.delenv("ASSASSYN_KEEP_BUILD_ARTIFACTS", raising=False)
+    verilator._build_verilator_library(spec)
+    assert not keep.exists()
+
+    monkeypatch.setenv("ASSASSYN_KEEP_BUILD_ARTIFACTS", str(keep))
+    verilator._build_verilator_library(spec)
+    assert log.read_text().count("run") == 2
+    kept = keep / spec.crate_name
+    assert (kept / "adder.sv").read_text() == "module adder; endmodule"
+    assert (kept / "verilated" / "Vadder.cpp").read_text() == "// model\n"
+    assert (kept / "wrapper.cpp").read_text() == verilator._generate_wrapper_cpp(spec)
+
+
+def test_verilator_cache_is_opt_in(tmp_path, monkeypatch):
+    """Without ASSASSYN_VERILATOR_CACHE, or with a false-like value, Verilator always re-runs."""
+    spec, log = _prepare_cached_build(tmp_path, monkeypatch, "crate", "module adder; endmodule")
+    monkeypatch.delenv("ASSASSYN_VERILATOR_CACHE")
+    verilator._build_verilator_library(spec)
+    monkeypatch.setenv("ASSASSYN_VERILATOR_CACHE", "0")
+    verilator._build_verilator_library(spec)
+    assert log.read_text().count("run") == 2
+    assert not (tmp_path / "cache").exists()
+
+
+def test_verilator_cache_keys_on_toolchain(tmp_path, monkeypatch):
+    """A different Verilator release, compiler argv, or host machine misses the cache."""
+    spec, log = _prepare_cached_build(tmp_path, monkeypatch, "crate", "module adder; endmodule")
+    verilator._build_verilator_library(spec)
+    verilator._build_verilator_library(spec)
+    assert log.read_text().count("run") == 1
+
+    _fake_verilator(tmp_path, f"echo run >> '{log}'", version="Verilator 5.032")
+    verilator._build_verilator_library(spec)
+    assert log.read_text().count("run") == 2
+
+    monkeypatch.setattr(verilator_build, "compiler_command", lambda: ["/usr/bin/clang++"])
+    verilator._build_verilator_library(spec)
+    assert log.read_text().count("run") == 3
+
+    monkeypatch.setattr(verilator.platform, "machine", lambda: "riscv64")
+    verilator._build_verilator_library(spec)
+    assert log.read_text().count("run") == 4
+
+
+def test_aggregated_translation_unit(tmp_path):
+    """Aggregation splits nothing, bundles every generated source, and is preferred."""
+    spec = _make_spec(tmp_path)
+    obj_dir = tmp_path / "obj"
+    obj_dir.mkdir()
+    for name in ("Vadder.cpp", "Vadder___024root__0.cpp", "Vadder__Syms.cpp"):
+        (obj_dir / name).write_text("")
+    (tmp_path / "src").mkdir()
+    (tmp_path / "src" / "wrapper.cpp").write_text("")
+    include_dir = tmp_path / "include"
+
+    split_sources = verilator_build.gather_source_files(spec, obj_dir, include_dir)
+    assert obj_dir / "Vadder__Syms.cpp" in split_sources
+    cmd = verilator_build.verilator_command(spec, tmp_path / "a.sv", obj_dir)
+    assert "--output-split" not in cmd
+
+    spec.aggregate = True
+    cmd = verilator_build.verilator_command(spec, tmp_path / "a.sv", obj_dir)
+    assert cmd[cmd.index("--output-split") + 1] == "0"
+    aggregated = verilator_build.write_aggregated_source(spec, obj_dir)
+    assert aggregated.name == "Vadder__ALL.cpp"
+    assert aggregated.read_text().splitlines() == [
+        '#include "Vadder.cpp"',
+        '#include "Vadder__Syms.cpp"',
+        '#include "Vadder___024root__0.cpp"',
+    ]
+
+    sources = verilator_build.gather_source_files(spec, obj_dir, include_dir)
+    generated = [path for path in sources if path.parent == obj_dir]
+    assert generated == [aggregated]
+
+
+def test_top_module_override(tmp_path, monkeypatch):
+    """ASSASSYN_TOP_MODULE swaps the elaborated top but keeps the wrapper's class name."""
+    spec = _make_spec(tmp_path)
+    monkeypatch.delenv("ASSASSYN_TOP_MODULE", raising=False)
+    cmd = verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    assert cmd[cmd.index("--top-module") + 1] == "adder"
+    assert "--prefix" not in cmd
+
+    monkeypatch.setenv("ASSASSYN_TOP_MODULE", "adder_fast")
+    cmd = verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path / "obj")
+    assert cmd[cmd.index("--top-module") + 1] == "adder_fast"
+    assert cmd[cmd.index("--prefix") + 1] == "Vadder"
+    assert cmd.index("--prefix") < cmd.index("--Mdir")
+
+
+def test_top_module_override_rejects_bad_identifier(tmp_path, monkeypatch):
+    """An override that is not a legal identifier fails before Verilator runs."""
+    spec = _make_spec(tmp_path)
+    for value in ("1adder", "adder top", "adder;rm"):
+        monkeypatch.setenv("ASSASSYN_TOP_MODULE", value)
+        with pytest.raises(ValueError, match="ASSASSYN_TOP_MODULE"):
+            verilator_build.verilator_command(spec, tmp_path / "adder.sv", tmp_path / "obj")
+
+
+_COUNTER_MODEL = """#pragma once
+#include <cstdint>
+class Vadder {
+public:
+    uint8_t clk = 0, rst = 0, count = 0;
+    void eval() { if (clk && !prev) count = rst ? 0 : count + 1; prev = clk; }
+private:
+    uint8_t prev = 0;
+};
+"""
+
+
+_HAS_CXX = any(shutil.which(cxx) for cxx in ("c++", "g++", "clang++"))
+
+
+def _build_counter_crate(tmp_path: Path, monkeypatch) -> ExternalFFIModule:
+    """Build a clocked crate around the stub counter model with the host compiler."""
+    model_dir = tmp_path / "model"
+    model_dir.mkdir()
+    (model_dir / "Vadder.h").write_text(_COUNTER_MODEL)
+    include_dir = tmp_path / "inc"
+    include_dir.mkdir()
+    stub_runtime = "#pragma once\nstruct Verilated { static void debug(int) {} };\n"
+    (include_dir / "verilated.h").write_text(stub_runtime)
+    (include_dir / "verilated.cpp").write_text("")
+    exe = _fake_verilator(tmp_path, f"for arg; do mdir=$arg; done\ncp '{model_dir}'/* \"$mdir\"/")
+    monkeypatch.setenv("ASSASSYN_VERILATOR", str(exe))
+    monkeypatch.delenv("ASSASSYN_VERILATOR_CACHE", raising=False)
+    monkeypatch.setattr(
+        verilator, "resolve_verilator_paths", lambda: (include_dir, include_dir / "vltstd")
+    )
+
+    spec = _make_spec(tmp_path / "crate")
+    spec.has_clock = spec.has_reset = True
+    spec.standalone = verilator_build.standalone_enabled()
+    out = SimpleNamespace(dtype=UInt(8), direction="out")
+    spec.outputs = [verilator._dtype_to_port("count", out)]
+    (spec.crate_path / "rtl").mkdir(parents=True)
+    (spec.crate_path / "rtl" / "adder.sv").write_text("module adder; endmodule")
+    verilator._emit_crate_artifacts(spec)
+    return spec
+
+
+@pytest.mark.skipif(not _HAS_CXX, reason="needs a host C++ compiler")
+def test_standalone_simulator_runs(tmp_path, monkeypatch):
+    """The standalone sim_main links against the wrapper and steps the model from argv."""
+    monkeypatch.setenv("ASSASSYN_VERILATOR_STANDALONE", "1")
+    spec = _build_counter_crate(tmp_path, monkeypatch)
+
+    binary = spec.crate_path / "sim_adder"
+    result = subprocess.run([str(binary), "3"], check=True, capture_output=True, text=True)
+    assert result.stdout.splitlines() == [
+        "cycle 0 count=2",
+        "cycle 1 count=3",
+        "cycle 2 count=4",
+    ]
+
+
+@pytest.mark.skipif(
+    not _HAS_CXX or not shutil.which("cargo"), reason="needs a host C++ compiler and cargo"
+)
+def test_run_until_stops_on_output(tmp_path, monkeypatch):
+    """run_until stops at the tick where an output first matches and reports timeouts as None."""
+    spec = _build_counter_crate(tmp_path, monkeypatch)
+    harness = tmp_path / "harness"
+    (harness / "src").mkdir(parents=True)
+    (harness / "Cargo.toml").write_text(
+        "[package]\nname = \"harness\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n"
+        f"[dependencies]\nverilated_adder = {{ path = \"{spec.crate_path.as_posix()}\" }}\n\n"
+        "[workspace]\n"
+    )
+    (harness / "src" / "main.rs").write_text(
+        "use verilated_adder::VerilatedAdder;\n\n"
+        "fn main() {\n"
+        "    let mut model = VerilatedAdder::new();\n"
+        "    model.apply_reset(1);\n"
+        "    let start = model.get_count();\n"
+        "    let stop = model.run_until(10, |m| m.get_count() >= 4);\n"
+        "    let last = model.run_until(3, |m| m.get_count() >= 7);\n"
+        "    let timeout = model.run_until(2, |m| m.get_count() >= 100);\n"
+        "    println!(\"{start} {stop:?} {last:?} {timeout:?} {}\", model.get_count());\n"
+        "}\n"
+    )
+    result = subprocess.run(
+        ["cargo", "run", "--quiet", "--manifest-path", str(harness / "Cargo.toml")],
+        check=True,
+        capture_output=True,
+        text=True,
+    )
+    # Reset leaves count at 1; the second call only matches after its final tick.
+    assert result.stdout.split() == ["1", "Some(3)", "Some(3)", "None", "9"]
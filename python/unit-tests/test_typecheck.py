@@ -0,0 +1,75 @@
+"""Test operand width checks in the typecheck analysis"""
+
+from assassyn.analysis import typecheck
+from assassyn.frontend import SysBuilder, Module, Port, UInt, module
+
+
+class MixedAdder(Module):
+    """Module adding a UInt(8) to a UInt(16)"""
+    def __init__(self):
+        super().__init__(ports={'a': Port(UInt(8)), 'b': Port(UInt(16))})
+
+    @module.combinational
+    def build(self):
+        """Add the two ports"""
+        a, b = self.pop_all_ports(True)
+        _ = a + b
+
+
+class WideSlice(Module):
+    """Module slicing past the top bit of a UInt(8)"""
+    def __init__(self):
+        super().__init__(ports={'a': Port(UInt(8))})
+
+    @module.combinational
+    def build(self):
+        """Slice bits 4 to 8"""
+        a = self.pop_all_ports(True)
+        _ = a[4:8]
+
+
+class WellTyped(Module):
+    """Module whose operands all fit their opcodes"""
+    def __init__(self):
+        super().__init__(ports={'a': Port(UInt(8)), 'b': Port(UInt(8))})
+
+    @module.combinational
+    def build(self):
+        """Add the ports and slice the sum"""
+        a, b = self.pop_all_ports(True)
+        _ = (a + b)[0:7]
+
+
+def test_width_mismatched_add():
+    """An add of 8- and 16-bit operands is reported with both widths"""
+    sys = SysBuilder('test_typecheck_width_mismatched_add')
+    with sys:
+        MixedAdder().build()
+
+    violations = typecheck(sys)
+    assert len(violations) == 1
+    assert violations[0].opcode == '+'
+    assert violations[0].actual == '8 and 16 bits'
+    assert 'expected operands of equal width' in str(violations[0])
+
+
+def test_out_of_range_slice():
+    """A slice ending past the source width is reported with its bounds"""
+    sys = SysBuilder('test_typecheck_out_of_range_slice')
+    with sys:
+        WideSlice().build()
+
+    violations = typecheck(sys)
+    assert len(violations) == 1
+    assert violations[0].opcode == 'slice'
+    assert violations[0].expected == '0 <= l <= r < 8'
+    assert violations[0].actual == '[4:8] of 8 bits'
+
+
+def test_well_typed_system():
+    """Matching operand widths and in-range slices produce no violations"""
+    sys = SysBuilder('test_typecheck_well_typed_system')
+    with sys:
+        WellTyped().build()
+
+    assert not typecheck(sys)